@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use ethers::abi::{encode, Token};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// A single part of a decomposed command template: either a fixed literal
+/// string or a capture group that binds one argument of the command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TemplatePart {
+    Literal(String),
+    Capture {
+        #[serde(rename = "type")]
+        capture_type: String,
+        is_public: bool,
+    },
+}
+
+/// A decomposed-regex definition for a single zkEmail command, e.g.
+/// `"recoverAccount {ethAddr} using {uint}"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandTemplate {
+    pub template_id: String,
+    pub command: String,
+    pub parts: Vec<TemplatePart>,
+}
+
+impl CommandTemplate {
+    /// Load a command template definition from a JSON file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read command template at {}", path.display()))?;
+        serde_json::from_str(&raw)
+            .with_context(|| format!("Failed to parse command template at {}", path.display()))
+    }
+
+    fn capture_count(&self) -> usize {
+        self.parts
+            .iter()
+            .filter(|part| matches!(part, TemplatePart::Capture { .. }))
+            .count()
+    }
+
+    /// Substitute `args` into this template's capture groups, in order, to
+    /// build the content of the email `Subject:` line.
+    pub fn render_subject(&self, args: &[String]) -> Result<String> {
+        if args.len() != self.capture_count() {
+            bail!(
+                "wrong command format: template {} expects {} argument(s), got {}",
+                self.template_id,
+                self.capture_count(),
+                args.len()
+            );
+        }
+
+        let mut rendered = String::new();
+        let mut args_iter = args.iter();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => rendered.push_str(text),
+                TemplatePart::Capture { .. } => {
+                    rendered.push_str(args_iter.next().expect("arity checked above"))
+                }
+            }
+        }
+        Ok(rendered)
+    }
+
+    /// Build the regex that matches a rendered subject for this template,
+    /// with one capture group per `TemplatePart::Capture`.
+    fn to_regex(&self) -> Result<Regex> {
+        let mut pattern = String::from("^");
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => pattern.push_str(&regex::escape(text)),
+                TemplatePart::Capture { capture_type, .. } => {
+                    pattern.push_str(capture_pattern(capture_type))
+                }
+            }
+        }
+        pattern.push('$');
+        Regex::new(&pattern)
+            .with_context(|| format!("Failed to compile regex for template {}", self.template_id))
+    }
+
+    /// The declared `capture_type` of each *public* capture group, in
+    /// template order, matching the order `extract_substr_idxes` returns.
+    fn public_capture_types(&self) -> Vec<&str> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                TemplatePart::Capture {
+                    capture_type,
+                    is_public: true,
+                } => Some(capture_type.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Scan `subject` against this template's regex and return the byte-index
+    /// range of each *public* capture group, in template order.
+    pub fn extract_substr_idxes(&self, subject: &str) -> Result<Vec<(usize, usize)>> {
+        let regex = self.to_regex()?;
+        let captures = regex.captures(subject).with_context(|| {
+            format!(
+                "wrong command format: \"{}\" does not match template {}",
+                subject, self.template_id
+            )
+        })?;
+
+        let mut idxes = Vec::new();
+        let mut group_idx = 1;
+        for part in &self.parts {
+            if let TemplatePart::Capture { is_public, .. } = part {
+                let matched = captures
+                    .get(group_idx)
+                    .context("wrong command format: capture group did not match")?;
+                if *is_public {
+                    idxes.push((matched.start(), matched.end()));
+                }
+                group_idx += 1;
+            }
+        }
+        Ok(idxes)
+    }
+
+    /// Slice the public captures out of `subject` and ABI-encode them as
+    /// `command_params`, mapping each capture's declared type to the matching
+    /// Solidity ABI type rather than encoding everything as `string`.
+    pub fn encode_command_params(&self, subject: &str) -> Result<Vec<u8>> {
+        let idxes = self.extract_substr_idxes(subject)?;
+        let types = self.public_capture_types();
+        let tokens: Vec<Token> = idxes
+            .into_iter()
+            .zip(types)
+            .map(|((start, end), capture_type)| capture_token(capture_type, &subject[start..end]))
+            .collect::<Result<_>>()?;
+        Ok(encode(&tokens))
+    }
+}
+
+/// Convert a matched capture string into the `Token` variant matching its
+/// declared `capture_type`, falling back to `Token::String` for unrecognized
+/// types.
+fn capture_token(capture_type: &str, value: &str) -> Result<Token> {
+    Ok(match capture_type {
+        "uint" | "int" => Token::Uint(ethers::types::U256::from_dec_str(value)?),
+        "ethAddr" => Token::Address(value.parse()?),
+        "bytes32" => Token::FixedBytes(
+            ethers::utils::hex::decode(value.trim_start_matches("0x"))
+                .context("Invalid bytes32 capture")?,
+        ),
+        _ => Token::String(value.to_string()),
+    })
+}
+
+/// The regex fragment used to capture a given Solidity-ish argument type.
+fn capture_pattern(capture_type: &str) -> &'static str {
+    match capture_type {
+        "uint" | "int" => r"(\d+)",
+        "ethAddr" => r"(0x[0-9a-fA-F]{40})",
+        "bytes32" => r"(0x[0-9a-fA-F]{64})",
+        _ => r"(\S+)",
+    }
+}
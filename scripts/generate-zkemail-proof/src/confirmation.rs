@@ -0,0 +1,23 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// Extract the confirmation token embedded in a reply email's
+/// `In-Reply-To` or `References` header, whichever appears first (its first
+/// quoted id), e.g. `<TOKEN@domain>` -> `TOKEN`.
+pub fn extract_token(raw_reply_email: &str) -> Result<String> {
+    let captures = Regex::new(r"(?m)^(?:In-Reply-To|References):\s*<([^@>]+)@[^>]+>")
+        .unwrap()
+        .captures(raw_reply_email)
+        .context("Reply email has no In-Reply-To/References header quoting a request token")?;
+    Ok(captures[1].to_string())
+}
+
+/// Extract a reply email's own `Message-ID` header, recorded on the request
+/// once it has been confirmed.
+pub fn extract_message_id(raw_email: &str) -> Result<String> {
+    let captures = Regex::new(r"(?m)^Message-ID:\s*(<[^>]+>)")
+        .unwrap()
+        .captures(raw_email)
+        .context("Reply email has no Message-ID header")?;
+    Ok(captures[1].to_string())
+}
@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use ethers::types::H256;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use trust_dns_resolver::TokioAsyncResolver;
+
+use email_tx_builder::{chain::ChainClient, dkim::check_and_update_dkim, RelayerState};
+
+use crate::transport::Transport;
+
+/// Resolve the `dkim_contract_address` for `raw_email`, per `transport`.
+///
+/// `Mock` and `File` never produce a genuinely DKIM-signed email (no real
+/// mail server was involved), so there is no real key to verify; we stub the
+/// address rather than hitting DNS/chain, keeping those transports fully
+/// offline. Only `Smtp` carries a real signature worth checking against the
+/// on-chain registry.
+pub async fn resolve(
+    transport: Transport,
+    raw_email: &str,
+    domain: &str,
+    relayer_state: &RelayerState,
+) -> Result<H256> {
+    match transport {
+        Transport::Mock | Transport::File => Ok(H256::zero()),
+        Transport::Smtp => {
+            let chain_client = ChainClient::new("sepolia", relayer_state)
+                .context("Failed to build chain client")?;
+            verify_and_register_dkim(raw_email, domain, &chain_client).await
+        }
+    }
+}
+
+/// Extract the DKIM selector (the `s=` tag) from a raw email's
+/// `DKIM-Signature` header.
+pub fn extract_selector(raw_email: &str) -> Result<String> {
+    let header = Regex::new(r"(?m)^DKIM-Signature:.*$")
+        .unwrap()
+        .find(raw_email)
+        .context("Email has no DKIM-Signature header")?
+        .as_str();
+
+    let selector = Regex::new(r"s=([^;]+);")
+        .unwrap()
+        .captures(header)
+        .and_then(|captures| captures.get(1))
+        .context("DKIM-Signature header has no selector (s=) tag")?;
+
+    Ok(selector.as_str().trim().to_string())
+}
+
+/// Fetch the RSA public key published at `{selector}._domainkey.{domain}` and
+/// return its raw base64 `p=` value together with its sha256 hash.
+pub async fn fetch_public_key(domain: &str, selector: &str) -> Result<(String, H256)> {
+    let resolver =
+        TokioAsyncResolver::tokio_from_system_conf().context("Failed to build DNS resolver")?;
+    let fqdn = format!("{}._domainkey.{}", selector, domain);
+
+    let lookup = resolver
+        .txt_lookup(fqdn.clone())
+        .await
+        .with_context(|| format!("Failed to resolve DKIM TXT record for {}", fqdn))?;
+    let record = lookup
+        .iter()
+        .next()
+        .with_context(|| format!("No DKIM TXT record found for {}", fqdn))?
+        .to_string();
+
+    let public_key = record
+        .split(';')
+        .find_map(|tag| tag.trim().strip_prefix("p="))
+        .with_context(|| format!("DKIM TXT record for {} has no public key (p=) tag", fqdn))?
+        .to_string();
+
+    let mut hasher = Sha256::new();
+    hasher.update(public_key.as_bytes());
+    let key_hash = H256::from_slice(&hasher.finalize());
+
+    Ok((public_key, key_hash))
+}
+
+/// Resolve the signing domain's DKIM selector and public key from
+/// `raw_email`, then confirm (or register) the key hash against the
+/// on-chain DKIM registry, returning the registry's contract address.
+pub async fn verify_and_register_dkim(
+    raw_email: &str,
+    domain: &str,
+    chain_client: &ChainClient,
+) -> Result<H256> {
+    let selector = extract_selector(raw_email)?;
+    let (_public_key, key_hash) = fetch_public_key(domain, &selector).await?;
+
+    check_and_update_dkim(chain_client, domain, &selector, key_hash)
+        .await
+        .with_context(|| {
+            format!(
+                "DKIM key for {} (selector {}) failed verification against the on-chain registry",
+                domain, selector
+            )
+        })
+}
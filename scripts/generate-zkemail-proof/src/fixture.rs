@@ -0,0 +1,55 @@
+use ethers::types::H256;
+use regex::Regex;
+
+/// An account_salt deliberately different from the one used to sign the
+/// command email, for the `MismatchedAccountSalt` fixture.
+pub const WRONG_ACCOUNT_SALT: &str =
+    "0x000000000000000000000000000000000000000000000000000000000000bad1";
+
+/// Which field (if any) a generated fixture should corrupt, for exercising
+/// the contract-side rejection paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FixtureSpec {
+    /// A structurally and cryptographically valid proof
+    Valid,
+    /// The DKIM body hash (`bh=`) is tampered with after the email is signed
+    TamperedDkimBodyHash,
+    /// The request's account_salt doesn't match the one the command was signed with
+    MismatchedAccountSalt,
+    /// The `EmailAuthMsg.template_id` doesn't match the encoded `command_params`
+    TemplateIdMismatch,
+    /// `dkim_contract_address` is wrong/zero instead of the verified registry address
+    WrongDkimContractAddress,
+    /// The email's Subject doesn't match the command template's regex
+    InvalidSubjectFormat,
+}
+
+impl FixtureSpec {
+    /// A short, file-name-safe label recorded in the fixture JSON and used
+    /// to name its output file in batch mode.
+    pub fn label(self) -> &'static str {
+        match self {
+            FixtureSpec::Valid => "valid",
+            FixtureSpec::TamperedDkimBodyHash => "tampered-dkim-body-hash",
+            FixtureSpec::MismatchedAccountSalt => "mismatched-account-salt",
+            FixtureSpec::TemplateIdMismatch => "template-id-mismatch",
+            FixtureSpec::WrongDkimContractAddress => "wrong-dkim-contract-address",
+            FixtureSpec::InvalidSubjectFormat => "invalid-subject-format",
+        }
+    }
+}
+
+/// Replace the `bh=` tag of a raw email's DKIM-Signature header with a
+/// tampered value, invalidating the body hash without touching the rest of
+/// the signature.
+pub fn tamper_dkim_body_hash(raw_email: &str) -> String {
+    Regex::new(r"bh=[^;]*;")
+        .unwrap()
+        .replace(raw_email, "bh=dGFtcGVyZWQ=;")
+        .to_string()
+}
+
+/// `H256::zero()`, used as the corrupted value for `WrongDkimContractAddress`.
+pub fn wrong_dkim_contract_address() -> H256 {
+    H256::zero()
+}
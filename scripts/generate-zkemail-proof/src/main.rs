@@ -1,8 +1,9 @@
 use std::fs::{self, create_dir_all};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
 use ethers::types::{H256, U256};
 use relayer_utils::ParsedEmail;
 use serde_json::json;
@@ -11,59 +12,176 @@ use uuid::Uuid;
 
 use email_tx_builder::{
     abis::EmailAuthMsg,
-    command::get_encoded_command_params,
-    dkim::check_and_update_dkim,
-    model::{RequestModel, RequestStatus, EmailTxAuth},
+    model::{EmailTxAuth, RequestModel, RequestStatus},
     prove::generate_email_proof,
     RelayerState,
-    chain::ChainClient,
 };
 
+mod command_template;
+mod confirmation;
+mod dkim_resolver;
+mod fixture;
+mod request_store;
+mod transport;
+
+use command_template::CommandTemplate;
+use fixture::FixtureSpec;
+use transport::Transport;
+
+/// Generate zkEmail proof fixtures, either valid or deliberately corrupted
+/// for contract-side negative tests, from a fabricated email or a real
+/// command email delivered and signed by a live mail server.
+#[derive(Debug, Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Generate one proof fixture: the valid case, or a single labeled failure case
+    Single {
+        #[command(flatten)]
+        shared: SharedArgs,
+
+        /// Which field (if any) to corrupt
+        #[arg(long, value_enum, default_value = "valid")]
+        failure: FixtureSpec,
+
+        /// Where the generated proof fixture is written
+        #[arg(long, default_value = "../test/fixtures/zkemail/valid-proof.json")]
+        output_path: String,
+    },
+    /// Generate the valid fixture plus the full suite of adversarial fixtures
+    Batch {
+        #[command(flatten)]
+        shared: SharedArgs,
+
+        /// Directory fixtures are written to, one file per failure category
+        #[arg(long, default_value = "../test/fixtures/zkemail")]
+        output_dir: String,
+    },
+    /// Phase one of a two-step (e.g. guardian/recovery) command: send the
+    /// initial request email and persist it, pending confirmation
+    Request {
+        #[command(flatten)]
+        shared: SharedArgs,
+
+        /// Path to the sled db the pending request is persisted in
+        #[arg(long, default_value = "./relayer-db")]
+        db_path: String,
+    },
+    /// Phase two: consume the confirmation reply and generate the proof
+    Confirm {
+        /// Path to the sled db the pending request was persisted in
+        #[arg(long, default_value = "./relayer-db")]
+        db_path: String,
+
+        /// Confirmation token printed by the `request` subcommand
+        #[arg(long)]
+        token: String,
+
+        /// Raw .eml file containing the confirmation reply email
+        #[arg(long)]
+        reply_path: PathBuf,
+
+        /// Where the generated proof fixture is written
+        #[arg(long, default_value = "../test/fixtures/zkemail/confirmed-proof.json")]
+        output_path: String,
+    },
+}
+
+#[derive(Debug, Clone, clap::Args)]
+struct SharedArgs {
+    /// Path to the JSON command-template definition to render the Subject from
+    #[arg(long, default_value = "./templates/sign_hash.json")]
+    template_path: String,
+
+    /// Arguments substituted into the template's capture groups, in order
+    #[arg(long = "arg")]
+    args: Vec<String>,
+
+    /// Domain to sign the command email with
+    #[arg(long, default_value = "example.com")]
+    domain: String,
+
+    /// Account salt for the EmailTxAuth
+    #[arg(
+        long,
+        default_value = "0x046582bce36cdd0a8953b9d40b8f20d58302bacf3bcecffeb6741c98a52725e2"
+    )]
+    account_salt: String,
+
+    /// How the command email is delivered: mock (default), file, or smtp
+    #[arg(long, value_enum, default_value = "mock")]
+    transport: Transport,
+
+    /// Directory `.eml` files are written to when --transport file is used
+    #[arg(long)]
+    outgoing_dir: Option<PathBuf>,
+}
+
+impl SharedArgs {
+    fn args_or_default(&self) -> Vec<String> {
+        if self.args.is_empty() {
+            // 0x0123456789abcdef... (repeated 4 times), in decimal for the `{uint}` capture
+            vec![
+                "514631507721405306298073637848375664226723355710112857507800679889911926255"
+                    .to_string(),
+            ]
+        } else {
+            self.args.clone()
+        }
+    }
+}
+
 // Create a simple logger for output
 fn setup_logger() -> Logger {
     slog::Logger::root(slog::Discard, o!())
 }
 
-// Generate a sample email with a signHash command
-fn generate_sample_email(hash: &str, domain: &str, account_salt: &str) -> String {
+// Generate a sample email with the given Subject line and Message-ID local part
+fn generate_sample_email(subject: &str, domain: &str, message_id: &str) -> String {
     format!(
         "From: test@{}\r\n\
          To: relayer@example.com\r\n\
-         Subject: signHash {}\r\n\
-         Message-ID: <test123@{}>\r\n\
+         Subject: {}\r\n\
+         Message-ID: <{}@{}>\r\n\
          Date: Thu, 21 Mar 2024 12:00:00 +0000\r\n\
          DKIM-Signature: v=1; a=rsa-sha256; d={}; s=selector; h=from:to:subject; bh=base64==; b=signature==\r\n\
          \r\n\
-         This is a test email to sign hash {}.\r\n",
-        domain, hash, domain, domain, hash
+         This is a test email for command: {}.\r\n",
+        domain, subject, message_id, domain, domain, subject
     )
 }
 
 // Mock a request model for the proof generation
-fn create_mock_request(template_id: &str, account_salt: &str) -> RequestModel {
-    RequestModel {
+fn create_mock_request(
+    template: &CommandTemplate,
+    subject: &str,
+    account_salt: &str,
+    dkim_contract_address: H256,
+) -> Result<RequestModel> {
+    Ok(RequestModel {
         id: Uuid::new_v4(),
-        subject: "signHash".to_string(),
+        subject: subject.to_string(),
         email_tx_auth: EmailTxAuth {
-            template_id: U256::from_str(template_id).unwrap(),
-            account_salt: Some(H256::from_str(account_salt).unwrap()),
+            template_id: U256::from_str(&template.template_id)?,
+            account_salt: Some(H256::from_str(account_salt)?),
             chain: Some("sepolia".to_string()),
-            dkim_contract_address: Some(H256::zero().to_string()),
+            dkim_contract_address: Some(dkim_contract_address.to_string()),
         },
         status: RequestStatus::Received,
         from_email: Some("test@example.com".to_string()),
         reply_to_message_id: None,
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
-    }
+    })
 }
 
-// Setup a mock RelayerState for proof generation
-fn create_mock_relayer_state() -> RelayerState {
-    let logger = setup_logger();
-    
-    // Create a minimal configuration
-    let config = email_tx_builder::config::Config {
+// Create a minimal configuration shared by every RelayerState we build here
+fn mock_config() -> email_tx_builder::config::Config {
+    email_tx_builder::config::Config {
         modal_token_id: None,
         modal_token_secret: None,
         domain: "example.com".to_string(),
@@ -76,85 +194,306 @@ fn create_mock_relayer_state() -> RelayerState {
         imap_url: "http://localhost:3001".to_string(),
         chains: vec![],
         prover_url: "http://localhost:3002".to_string(),
-    };
-    
+    }
+}
+
+// Setup a mock RelayerState, backed by a throwaway db, for single-shot proof generation
+fn create_mock_relayer_state() -> RelayerState {
     RelayerState {
-        config,
+        config: mock_config(),
         db: sled::Config::new().temporary(true).open().unwrap(),
         http_client: reqwest::Client::new(),
-        logger,
+        logger: setup_logger(),
     }
 }
 
-// Main function to generate and save the proof
-async fn generate_proof(
-    hash: &str, 
-    domain: &str,
-    account_salt: &str, 
-    template_id: &str,
-    output_path: &str
-) -> Result<()> {
-    let email = generate_sample_email(hash, domain, account_salt);
-    let request = create_mock_request(template_id, account_salt);
+// Setup a RelayerState backed by a persistent db, so a pending request stored
+// by `request` can be looked up again by `confirm` in a later invocation
+fn open_relayer_state(db_path: &str) -> Result<RelayerState> {
+    let db =
+        sled::open(db_path).with_context(|| format!("Failed to open relayer db at {}", db_path))?;
+    Ok(RelayerState {
+        config: mock_config(),
+        db,
+        http_client: reqwest::Client::new(),
+        logger: setup_logger(),
+    })
+}
+
+// Generate and save a proof fixture, optionally corrupted per `spec` for
+// contract-side negative tests
+async fn generate_proof(shared: &SharedArgs, output_path: &str, spec: FixtureSpec) -> Result<()> {
+    let template = CommandTemplate::load(Path::new(&shared.template_path))?;
+    let args = shared.args_or_default();
+
+    let subject = if spec == FixtureSpec::InvalidSubjectFormat {
+        // Deliberately doesn't match the template's regex.
+        format!("{} but not quite", template.render_subject(&args)?)
+    } else {
+        template.render_subject(&args)?
+    };
+
+    let mock_email = generate_sample_email(&subject, &shared.domain, "test123");
     let relayer_state = create_mock_relayer_state();
-    
+
+    let mut email = transport::deliver_command_email(
+        shared.transport,
+        &mock_email,
+        &subject,
+        &relayer_state.config.smtp_url,
+        &relayer_state.config.imap_url,
+        shared.outgoing_dir.as_deref(),
+    )
+    .await
+    .context("Failed to deliver command email")?;
+
+    if spec == FixtureSpec::TamperedDkimBodyHash {
+        email = fixture::tamper_dkim_body_hash(&email);
+    }
+
+    // Resolve the signing domain's DKIM key and confirm it against the
+    // on-chain registry before we prove anything on top of it (skipped for
+    // the offline mock/file transports, which never produce a real signature).
+    let dkim_contract_address = if spec == FixtureSpec::WrongDkimContractAddress {
+        fixture::wrong_dkim_contract_address()
+    } else {
+        dkim_resolver::resolve(shared.transport, &email, &shared.domain, &relayer_state)
+            .await
+            .context("Failed to resolve DKIM key against the on-chain registry")?
+    };
+
+    let account_salt = if spec == FixtureSpec::MismatchedAccountSalt {
+        fixture::WRONG_ACCOUNT_SALT.to_string()
+    } else {
+        shared.account_salt.clone()
+    };
+
+    let request = create_mock_request(&template, &subject, &account_salt, dkim_contract_address)?;
+
     // Parse the email
     let parsed_email = ParsedEmail::new_from_raw_email(&email)
         .await
         .context("Failed to parse email")?;
-    
+
     info!(relayer_state.logger, "Parsed email: {:?}", parsed_email);
-    
-    // Generate command params
-    let command_params_encoded = get_encoded_command_params(&email, request.clone())
-        .await
-        .context("Failed to get encoded command params")?;
-    
+
+    // Generate command params by scanning the rendered Subject against the
+    // arbitrary, data-driven template rather than the library's fixed
+    // `config.path.email_templates` lookup. An invalid-subject fixture is
+    // expected to fail here; record empty params rather than a structurally
+    // valid encoding.
+    let command_params_encoded = match template.encode_command_params(&subject) {
+        Ok(params) => params,
+        Err(err) if spec == FixtureSpec::InvalidSubjectFormat => {
+            info!(
+                relayer_state.logger,
+                "expected command-parsing failure: {:?}", err
+            );
+            Vec::new()
+        }
+        Err(err) => return Err(err).context("Failed to encode command params"),
+    };
+
     // Generate the email proof
     let email_proof = generate_email_proof(&email, request.clone(), relayer_state.clone())
         .await
         .context("Failed to generate email proof")?;
-    
+
+    let template_id = if spec == FixtureSpec::TemplateIdMismatch {
+        request.email_tx_auth.template_id + U256::one()
+    } else {
+        request.email_tx_auth.template_id
+    };
+
     // Create the EmailAuthMsg
     let email_auth_msg = EmailAuthMsg {
-        template_id: request.email_tx_auth.template_id,
+        template_id,
         command_params: command_params_encoded,
         skipped_command_prefix: U256::zero(),
         proof: email_proof,
     };
-    
+
     // Convert to JSON
     let json_output = json!({
         "emailAuthMsg": email_auth_msg,
-        "hash": hash,
-        "domain": domain,
+        "command": subject,
+        "domain": shared.domain,
         "accountSalt": account_salt,
-        "templateId": template_id,
+        "templateId": template.template_id,
+        "expectedFailure": (spec != FixtureSpec::Valid).then(|| spec.label()),
     });
-    
+
     // Ensure output directory exists
     let output_dir = Path::new(output_path).parent().unwrap_or(Path::new("."));
     create_dir_all(output_dir).context("Failed to create output directory")?;
-    
+
     // Write to file
     fs::write(output_path, serde_json::to_string_pretty(&json_output)?)
         .context("Failed to write proof to file")?;
-    
+
     println!("Successfully generated and saved proof to {}", output_path);
     Ok(())
 }
 
+// Phase one of a two-step command: send the initial request email, keyed by
+// a random confirmation token embedded in its Message-ID, and persist it as
+// `Received` pending the confirmation reply
+async fn request_confirmation(shared: &SharedArgs, db_path: &str) -> Result<()> {
+    let template = CommandTemplate::load(Path::new(&shared.template_path))?;
+    let args = shared.args_or_default();
+    let subject = template.render_subject(&args)?;
+    let token = request_store::generate_token();
+
+    let mock_email = generate_sample_email(&subject, &shared.domain, &token);
+    let relayer_state = open_relayer_state(db_path)?;
+
+    let email = transport::deliver_command_email(
+        shared.transport,
+        &mock_email,
+        &subject,
+        &relayer_state.config.smtp_url,
+        &relayer_state.config.imap_url,
+        shared.outgoing_dir.as_deref(),
+    )
+    .await
+    .context("Failed to deliver initial request email")?;
+
+    let dkim_contract_address =
+        dkim_resolver::resolve(shared.transport, &email, &shared.domain, &relayer_state)
+            .await
+            .context("Failed to resolve DKIM key against the on-chain registry")?;
+
+    let request = create_mock_request(
+        &template,
+        &subject,
+        &shared.account_salt,
+        dkim_contract_address,
+    )?;
+    request_store::store_pending_request(
+        &relayer_state.db,
+        &token,
+        &request,
+        &shared.template_path,
+    )
+    .context("Failed to persist pending request")?;
+
+    println!(
+        "Stored pending request for token {} (status: {:?}); awaiting confirmation reply",
+        token, request.status
+    );
+    Ok(())
+}
+
+// Phase two: consume a reply email that quotes the confirmation token,
+// advance the stored request to `Confirmed`, and generate its proof
+async fn confirm_request(
+    db_path: &str,
+    token: &str,
+    reply_path: &Path,
+    output_path: &str,
+) -> Result<()> {
+    let relayer_state = open_relayer_state(db_path)?;
+    let raw_reply = fs::read_to_string(reply_path)
+        .with_context(|| format!("Failed to read reply email at {}", reply_path.display()))?;
+
+    let quoted_token = confirmation::extract_token(&raw_reply)?;
+    if quoted_token != token {
+        bail!(
+            "reply email quotes token {} but --token {} was given",
+            quoted_token,
+            token
+        );
+    }
+
+    let pending = request_store::load_pending_request(&relayer_state.db, token)?;
+    let mut request = pending.request;
+    request.reply_to_message_id = Some(confirmation::extract_message_id(&raw_reply)?);
+    request.status = RequestStatus::Confirmed;
+    request.updated_at = chrono::Utc::now();
+    request_store::store_pending_request(
+        &relayer_state.db,
+        token,
+        &request,
+        &pending.template_path,
+    )
+    .context("Failed to persist confirmed request")?;
+
+    let parsed_email = ParsedEmail::new_from_raw_email(&raw_reply)
+        .await
+        .context("Failed to parse reply email")?;
+    info!(
+        relayer_state.logger,
+        "Parsed confirmation reply: {:?}", parsed_email
+    );
+
+    // Re-derive command_params from the same data-driven template the
+    // initial request was rendered from, consistent with `generate_proof`,
+    // rather than the library's fixed `config.path.email_templates` lookup.
+    let template = CommandTemplate::load(Path::new(&pending.template_path))?;
+    let command_params_encoded = template
+        .encode_command_params(&request.subject)
+        .context("Failed to encode command params")?;
+    let email_proof = generate_email_proof(&raw_reply, request.clone(), relayer_state.clone())
+        .await
+        .context("Failed to generate email proof")?;
+
+    let email_auth_msg = EmailAuthMsg {
+        template_id: request.email_tx_auth.template_id,
+        command_params: command_params_encoded,
+        skipped_command_prefix: U256::zero(),
+        proof: email_proof,
+    };
+
+    let json_output = json!({
+        "emailAuthMsg": email_auth_msg,
+        "token": token,
+        "status": format!("{:?}", request.status),
+        "replyToMessageId": request.reply_to_message_id,
+    });
+
+    let output_dir = Path::new(output_path).parent().unwrap_or(Path::new("."));
+    create_dir_all(output_dir).context("Failed to create output directory")?;
+    fs::write(output_path, serde_json::to_string_pretty(&json_output)?)
+        .context("Failed to write proof to file")?;
+
+    println!(
+        "Successfully generated and saved confirmed proof to {}",
+        output_path
+    );
+    Ok(())
+}
+
 // Entry point for the script
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Example values
-    let hash = "0x0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
-    let domain = "example.com";
-    let account_salt = "0x046582bce36cdd0a8953b9d40b8f20d58302bacf3bcecffeb6741c98a52725e2";
-    let template_id = "0x0000000000000000000000000000000000000000000000000000000000000001";
-    let output_path = "../test/fixtures/zkemail/valid-proof.json";
-    
-    generate_proof(hash, domain, account_salt, template_id, output_path).await?;
-    
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Single {
+            shared,
+            failure,
+            output_path,
+        } => {
+            generate_proof(&shared, &output_path, failure).await?;
+        }
+        Command::Batch { shared, output_dir } => {
+            for spec in FixtureSpec::value_variants() {
+                let output_path = format!("{}/{}-proof.json", output_dir, spec.label());
+                generate_proof(&shared, &output_path, *spec).await?;
+            }
+        }
+        Command::Request { shared, db_path } => {
+            request_confirmation(&shared, &db_path).await?;
+        }
+        Command::Confirm {
+            db_path,
+            token,
+            reply_path,
+            output_path,
+        } => {
+            confirm_request(&db_path, &token, &reply_path, &output_path).await?;
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,56 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use email_tx_builder::model::RequestModel;
+
+const KEY_PREFIX: &str = "pending-request:";
+
+/// A pending request together with the command-template path it was
+/// rendered from, so phase two can re-derive `command_params` from the same
+/// template rather than a fixed lookup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingRequest {
+    pub request: RequestModel,
+    pub template_path: String,
+}
+
+/// Generate a URL-safe, unpadded base64 confirmation token for a two-phase
+/// request/reply command.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Persist `request` and the template path it was rendered from in the
+/// relayer's sled db, keyed by its confirmation token, so both can be looked
+/// up again once the confirmation reply arrives.
+pub fn store_pending_request(
+    db: &sled::Db,
+    token: &str,
+    request: &RequestModel,
+    template_path: &str,
+) -> Result<()> {
+    let pending = PendingRequest {
+        request: request.clone(),
+        template_path: template_path.to_string(),
+    };
+    let value = serde_json::to_vec(&pending).context("Failed to serialize pending request")?;
+    db.insert(format!("{}{}", KEY_PREFIX, token), value)
+        .context("Failed to persist pending request")?;
+    db.flush()
+        .context("Failed to flush pending request store")?;
+    Ok(())
+}
+
+/// Load the pending request and its template path, previously stored under `token`.
+pub fn load_pending_request(db: &sled::Db, token: &str) -> Result<PendingRequest> {
+    let value = db
+        .get(format!("{}{}", KEY_PREFIX, token))
+        .context("Failed to read pending request store")?
+        .with_context(|| format!("No pending request found for token {}", token))?;
+    serde_json::from_slice(&value).context("Failed to deserialize pending request")
+}
@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use lettre::message::Message;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use tokio::time::sleep;
+
+/// How the command email is delivered, and where the DKIM-signed reply is
+/// sourced from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Transport {
+    /// Fabricate a raw message with a placeholder DKIM-Signature header; no network access.
+    Mock,
+    /// Write the outgoing email to a `.eml` file under `--outgoing-dir` for inspection.
+    File,
+    /// Send the command email via SMTP and poll IMAP for the signed delivery.
+    Smtp,
+}
+
+const IMAP_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const IMAP_POLL_ATTEMPTS: u32 = 30;
+
+/// Deliver the rendered command email per `transport` and return the raw,
+/// DKIM-signed message to feed into `ParsedEmail::new_from_raw_email`.
+///
+/// `mock_email` is the locally-fabricated message, returned as-is for
+/// `Transport::Mock` and written out as-is for `Transport::File`. For
+/// `Transport::Smtp` only its `Message-ID` is reused, on a new message built
+/// from `subject`.
+pub async fn deliver_command_email(
+    transport: Transport,
+    mock_email: &str,
+    subject: &str,
+    smtp_url: &str,
+    imap_url: &str,
+    outgoing_dir: Option<&Path>,
+) -> Result<String> {
+    match transport {
+        Transport::Mock => Ok(mock_email.to_string()),
+        Transport::File => {
+            let path = write_outgoing_email(mock_email, subject, outgoing_dir)?;
+            bail!(
+                "wrote outgoing command email to {} for inspection; the file transport does not \
+                 poll for a reply, rerun with --transport smtp once a mail server is available",
+                path.display()
+            )
+        }
+        Transport::Smtp => {
+            send_via_smtp(mock_email, subject, smtp_url).await?;
+            poll_imap_for_reply(imap_url, subject).await
+        }
+    }
+}
+
+fn write_outgoing_email(
+    mock_email: &str,
+    subject: &str,
+    outgoing_dir: Option<&Path>,
+) -> Result<PathBuf> {
+    let dir = outgoing_dir.context("--outgoing-dir is required for the file transport")?;
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create outgoing directory {}", dir.display()))?;
+    let file_name = format!("{}.eml", slug(subject));
+    let path = dir.join(file_name);
+    std::fs::write(&path, mock_email)
+        .with_context(|| format!("Failed to write outgoing email to {}", path.display()))?;
+    Ok(path)
+}
+
+/// Send a genuine command email with `Subject: {subject}` through the
+/// configured SMTP server, so the reply we poll IMAP for actually quotes it.
+/// The message keeps `mock_email`'s `Message-ID` (and so any token in it).
+async fn send_via_smtp(mock_email: &str, subject: &str, smtp_url: &str) -> Result<()> {
+    let url = url::Url::parse(smtp_url).context("Failed to parse smtp_url")?;
+    let host = url.host_str().context("smtp_url is missing a host")?;
+    let port = url.port_or_known_default().unwrap_or(465);
+    let mut mailer = AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+        .context("Failed to build SMTP transport")?
+        .port(port);
+    if let Some(password) = url.password() {
+        mailer = mailer.credentials(Credentials::new(
+            url.username().to_string(),
+            password.to_string(),
+        ));
+    }
+    let message = Message::builder()
+        .from("relayer@example.com".parse()?)
+        .to("account@example.com".parse()?)
+        .message_id(crate::confirmation::extract_message_id(mock_email).ok())
+        .subject(subject.to_string())
+        .body(format!("Please confirm the command: {}\r\n", subject))
+        .context("Failed to build outgoing email")?;
+
+    mailer
+        .build()
+        .send(message)
+        .await
+        .context("Failed to send command email over SMTP")?;
+    Ok(())
+}
+
+/// Poll the configured IMAP mailbox until a DKIM-signed reply quoting
+/// `subject` shows up, or give up after `IMAP_POLL_ATTEMPTS` attempts.
+async fn poll_imap_for_reply(imap_url: &str, subject: &str) -> Result<String> {
+    let url = url::Url::parse(imap_url).context("Failed to parse imap_url")?;
+    let host = url
+        .host_str()
+        .context("imap_url is missing a host")?
+        .to_string();
+    let port = url.port().unwrap_or(993);
+    let username = url.username().to_string();
+    let password = url.password().unwrap_or_default().to_string();
+
+    for attempt in 1..=IMAP_POLL_ATTEMPTS {
+        let tls = native_tls::TlsConnector::new().context("Failed to build TLS connector")?;
+        let client = imap::connect((host.as_str(), port), &host, &tls)
+            .context("Failed to connect to IMAP server")?;
+        let mut session = client
+            .login(&username, &password)
+            .map_err(|(err, _)| err)
+            .context("Failed to authenticate with IMAP server")?;
+        session.select("INBOX").context("Failed to select INBOX")?;
+
+        let ids = session
+            .search(format!("SUBJECT \"{}\"", subject))
+            .context("Failed to search IMAP mailbox")?;
+        if let Some(&id) = ids.iter().next() {
+            let messages = session
+                .fetch(id.to_string(), "RFC822")
+                .context("Failed to fetch reply")?;
+            if let Some(message) = messages.iter().next() {
+                let raw = message.body().context("IMAP reply had no body")?;
+                let raw =
+                    String::from_utf8(raw.to_vec()).context("IMAP reply was not valid UTF-8")?;
+                session.logout().ok();
+                return Ok(raw);
+            }
+        }
+        session.logout().ok();
+
+        if attempt < IMAP_POLL_ATTEMPTS {
+            sleep(IMAP_POLL_INTERVAL).await;
+        }
+    }
+
+    bail!(
+        "timed out waiting for a DKIM-signed reply to \"{}\" in the IMAP mailbox",
+        subject
+    )
+}
+
+fn slug(subject: &str) -> String {
+    subject
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}